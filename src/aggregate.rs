@@ -1,25 +1,99 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
-use crate::collect::{Batch, Data};
+use crate::collect::{Batch, Data, ReplicaMetrics};
 use crate::pprof::StringTable;
 use crate::pprof::profile as pp;
-use crate::types::{Address, OpId, OpInfo, WorkerId};
+use crate::types::{
+    Address, ChannelId, ChannelInfo, DataflowId, ExportId, ExportInfo, LirId, LirInfo, ObjectId,
+    OpId, OpInfo, PeekId, ProcessId, WorkerId,
+};
+
+/// How to build the stacks of operator samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum StackMode {
+    /// follow the Timely operator hierarchy
+    Operator,
+    /// insert LIR operator frames between dataflows and their Timely operators
+    Lir,
+    /// nest dataflows below the SQL objects they depend on
+    Dependencies,
+}
 
 pub struct Aggregator {
+    cluster: String,
+    replica: String,
+    stacks: StackMode,
     start: Option<Duration>,
+    end: Option<Duration>,
     operators: BTreeMap<OpId, OpInfo>,
+    channels: BTreeMap<ChannelId, ChannelInfo>,
+    exports: BTreeMap<ExportId, ExportInfo>,
+    lir: BTreeMap<(ExportId, LirId), LirInfo>,
+    dependencies: BTreeMap<(ObjectId, ObjectId), i64>,
+    object_names: BTreeMap<ObjectId, String>,
     elapsed: BTreeMap<(OpId, WorkerId), Duration>,
+    schedules: BTreeMap<(OpId, WorkerId), i64>,
+    durations: BTreeMap<(OpId, WorkerId, u64), i64>,
+    parks: BTreeMap<(WorkerId, u64), i64>,
     sizes: BTreeMap<(OpId, WorkerId), i64>,
+    sharing: BTreeMap<(OpId, WorkerId), i64>,
+    capacities: BTreeMap<(OpId, WorkerId), i64>,
+    allocations: BTreeMap<(OpId, WorkerId), i64>,
+    records: BTreeMap<(OpId, WorkerId), i64>,
+    batches: BTreeMap<(OpId, WorkerId), i64>,
+    messages_sent: BTreeMap<(ChannelId, WorkerId), i64>,
+    messages_received: BTreeMap<(ChannelId, WorkerId), i64>,
+    message_batches_sent: BTreeMap<(ChannelId, WorkerId), i64>,
+    message_batches_received: BTreeMap<(ChannelId, WorkerId), i64>,
+    errors: BTreeMap<(ExportId, WorkerId), i64>,
+    hydration: BTreeMap<ExportId, Duration>,
+    export_frontiers: BTreeMap<(ExportId, Duration), i64>,
+    import_frontiers: BTreeMap<(ExportId, ExportId, Duration), i64>,
+    active_peeks: BTreeMap<PeekId, Duration>,
+    peeks: BTreeMap<ExportId, i64>,
+    peek_time: BTreeMap<ExportId, Duration>,
+    replica_metrics: BTreeMap<(ProcessId, ReplicaMetrics), i64>,
+    replica_cpu: Duration,
 }
 
 impl Aggregator {
-    pub fn new() -> Self {
+    pub fn new(cluster: &str, replica: &str, stacks: StackMode) -> Self {
         Self {
+            cluster: cluster.into(),
+            replica: replica.into(),
+            stacks,
             start: None,
+            end: None,
             operators: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            exports: BTreeMap::new(),
+            lir: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+            object_names: BTreeMap::new(),
             elapsed: BTreeMap::new(),
+            schedules: BTreeMap::new(),
+            durations: BTreeMap::new(),
+            parks: BTreeMap::new(),
             sizes: BTreeMap::new(),
+            sharing: BTreeMap::new(),
+            capacities: BTreeMap::new(),
+            allocations: BTreeMap::new(),
+            records: BTreeMap::new(),
+            batches: BTreeMap::new(),
+            messages_sent: BTreeMap::new(),
+            messages_received: BTreeMap::new(),
+            message_batches_sent: BTreeMap::new(),
+            message_batches_received: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            hydration: BTreeMap::new(),
+            export_frontiers: BTreeMap::new(),
+            import_frontiers: BTreeMap::new(),
+            active_peeks: BTreeMap::new(),
+            peeks: BTreeMap::new(),
+            peek_time: BTreeMap::new(),
+            replica_metrics: BTreeMap::new(),
+            replica_cpu: Duration::ZERO,
         }
     }
 
@@ -27,13 +101,67 @@ impl Aggregator {
         if self.start.is_none() {
             self.start = Some(batch.time);
         }
+        if let Some(end) = self.end {
+            self.integrate_replica_cpu(batch.time.saturating_sub(end));
+        }
+        self.end = Some(batch.time);
 
         for update in batch.updates {
             let diff = update.diff;
             match update.data {
                 Data::Operator(id, info) => self.update_operator(id, info, diff),
+                Data::Channel(id, info) => self.update_channel(id, info, diff),
+                Data::Export(id, info) => self.update_export(id, info, diff),
+                Data::Lir(id, lir_id, info) => self.update_lir(id, lir_id, info, diff),
+                Data::Dependency(id, referenced_id, referenced_name) => {
+                    self.update_dependency(id, referenced_id, referenced_name, diff);
+                }
                 Data::Elapsed(id, worker) => self.update_elapsed(id, worker, diff),
-                Data::Size(id, worker) => self.update_size(id, worker, diff),
+                Data::Schedules(id, worker) => {
+                    accumulate_counter(&mut self.schedules, (id, worker), diff);
+                }
+                Data::Durations(id, worker, bucket) => {
+                    accumulate_counter(&mut self.durations, (id, worker, bucket), diff);
+                }
+                Data::Parks(worker, bucket) => {
+                    accumulate_counter(&mut self.parks, (worker, bucket), diff);
+                }
+                Data::Size(id, worker) => accumulate(&mut self.sizes, (id, worker), diff),
+                Data::Sharing(id, worker) => accumulate(&mut self.sharing, (id, worker), diff),
+                Data::Capacity(id, worker) => {
+                    accumulate(&mut self.capacities, (id, worker), diff);
+                }
+                Data::Allocations(id, worker) => {
+                    accumulate(&mut self.allocations, (id, worker), diff);
+                }
+                Data::Records(id, worker) => accumulate(&mut self.records, (id, worker), diff),
+                Data::Batches(id, worker) => accumulate(&mut self.batches, (id, worker), diff),
+                Data::MessagesSent(id, worker) => {
+                    accumulate_counter(&mut self.messages_sent, (id, worker), diff);
+                }
+                Data::MessagesReceived(id, worker) => {
+                    accumulate_counter(&mut self.messages_received, (id, worker), diff);
+                }
+                Data::MessageBatchesSent(id, worker) => {
+                    accumulate_counter(&mut self.message_batches_sent, (id, worker), diff);
+                }
+                Data::MessageBatchesReceived(id, worker) => {
+                    accumulate_counter(&mut self.message_batches_received, (id, worker), diff);
+                }
+                Data::ErrorCounts(id, worker, count) => {
+                    accumulate(&mut self.errors, (id, worker), count * diff);
+                }
+                Data::Hydration(id, time) => self.update_hydration(id, time, diff),
+                Data::ExportFrontier(id, time) => {
+                    accumulate(&mut self.export_frontiers, (id, time), diff);
+                }
+                Data::ImportFrontier(id, import, time) => {
+                    accumulate(&mut self.import_frontiers, (id, import, time), diff);
+                }
+                Data::Peek(id, object) => self.update_peek(id, object, update.time, diff),
+                Data::ReplicaMetrics(id, metrics) => {
+                    accumulate(&mut self.replica_metrics, (id, metrics), diff);
+                }
             }
         }
     }
@@ -44,6 +172,79 @@ impl Aggregator {
         }
     }
 
+    fn update_channel(&mut self, id: ChannelId, info: ChannelInfo, diff: i64) {
+        if diff > 0 {
+            self.channels.insert(id, info);
+        }
+    }
+
+    fn update_export(&mut self, id: ExportId, info: ExportInfo, diff: i64) {
+        if diff > 0 {
+            self.exports.insert(id, info);
+        }
+    }
+
+    fn update_lir(&mut self, id: ExportId, lir_id: LirId, info: LirInfo, diff: i64) {
+        if diff > 0 {
+            self.lir.insert((id, lir_id), info);
+        }
+    }
+
+    fn update_dependency(
+        &mut self,
+        id: ObjectId,
+        referenced_id: ObjectId,
+        referenced_name: String,
+        diff: i64,
+    ) {
+        if diff > 0 {
+            self.object_names
+                .insert(referenced_id.clone(), referenced_name);
+        }
+        accumulate(&mut self.dependencies, (id, referenced_id), diff);
+    }
+
+    fn update_hydration(&mut self, id: ExportId, time: Duration, diff: i64) {
+        if diff > 0 {
+            self.hydration.insert(id, time);
+        }
+    }
+
+    /// Track a peek's lifetime, from its installation to its retirement.
+    ///
+    /// Introspection data is only updated periodically, so the measured latencies have a
+    /// granularity of the introspection interval.
+    fn update_peek(&mut self, id: PeekId, object: ExportId, time: Duration, diff: i64) {
+        if diff > 0 {
+            self.active_peeks.insert(id, time);
+            accumulate(&mut self.peeks, object, 1);
+        } else if let Some(start) = self.active_peeks.remove(&id) {
+            let latency = time.saturating_sub(start);
+            self.peek_time
+                .entry(object)
+                .and_modify(|x| *x += latency)
+                .or_insert(latency);
+        }
+    }
+
+    /// Add the CPU time the replica spent over the given interval, assuming its CPU usage
+    /// stayed constant.
+    fn integrate_replica_cpu(&mut self, interval: Duration) {
+        let nano_cores: u128 = self
+            .current_replica_metrics()
+            .map(|m| u128::from(m.cpu_nano_cores))
+            .sum();
+        let nanos = nano_cores * interval.as_nanos() / 1_000_000_000;
+        self.replica_cpu += Duration::from_nanos(nanos.try_into().unwrap());
+    }
+
+    fn current_replica_metrics(&self) -> impl Iterator<Item = &ReplicaMetrics> {
+        self.replica_metrics
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|((_, metrics), _)| metrics)
+    }
+
     fn update_elapsed(&mut self, id: OpId, worker: WorkerId, diff: i64) {
         if let Ok(nanos) = u64::try_from(diff) {
             let elapsed = Duration::from_nanos(nanos);
@@ -54,75 +255,609 @@ impl Aggregator {
         }
     }
 
-    fn update_size(&mut self, id: OpId, worker: WorkerId, diff: i64) {
-        self.sizes
-            .entry((id, worker))
-            .and_modify(|x| *x += diff)
-            .or_insert(diff);
-    }
-
-    pub fn build_pprof(&self) -> pp::Profile {
-        let mut builder = ProfileBuilder::new();
+    fn add_to_profile<'a>(&'a self, builder: &mut ProfileBuilder<'a>) {
+        builder.begin_scope(vec![
+            Label::Str("cluster", self.cluster.clone()),
+            Label::Str("replica", self.replica.clone()),
+        ]);
 
         if let Some(time) = self.start {
             builder.set_time(time);
         }
 
+        self.add_operators(builder);
+
+        if !self.elapsed.is_empty() {
+            let mut samples = to_sample_keys(self.elapsed_samples());
+            if !self.replica_metrics.is_empty() && !self.replica_cpu.is_zero() {
+                let total = self.replica_cpu.as_nanos().try_into().unwrap();
+                add_overhead_sample(&mut samples, total);
+            }
+            builder.add_samples("time", "nanoseconds", &samples);
+        }
+
+        if !self.schedules.is_empty() {
+            builder.add_samples("schedules", "count", &self.schedules);
+        }
+
+        if !self.durations.is_empty() {
+            let samples = self.duration_samples();
+            builder.add_samples("durations", "nanoseconds", &samples);
+        }
+
+        if !self.parks.is_empty() {
+            let samples = self.park_samples();
+            builder.add_samples("parks", "nanoseconds", &samples);
+        }
+
+        if !self.sizes.is_empty() {
+            let mut samples = self.size_samples();
+            if !self.replica_metrics.is_empty() {
+                let total: u64 = self.current_replica_metrics().map(|m| m.memory_bytes).sum();
+                add_overhead_sample(&mut samples, total.try_into().unwrap());
+            }
+            builder.add_samples("size", "bytes", &samples);
+        }
+
+        if !self.capacities.is_empty() {
+            builder.add_samples("capacity", "bytes", &self.capacities);
+        }
+
+        if !self.allocations.is_empty() {
+            builder.add_samples("allocations", "count", &self.allocations);
+        }
+
+        if !self.records.is_empty() {
+            builder.add_samples("records", "count", &self.records);
+        }
+
+        if !self.batches.is_empty() {
+            builder.add_samples("batches", "count", &self.batches);
+        }
+
+        if !self.messages_sent.is_empty() {
+            let samples = self.channel_samples(&self.messages_sent, |info| info.from);
+            builder.add_samples("messages-sent", "count", &samples);
+        }
+
+        if !self.messages_received.is_empty() {
+            let samples = self.channel_samples(&self.messages_received, |info| info.to);
+            builder.add_samples("messages-received", "count", &samples);
+        }
+
+        if !self.message_batches_sent.is_empty() {
+            let samples = self.channel_samples(&self.message_batches_sent, |info| info.from);
+            builder.add_samples("message-batches-sent", "count", &samples);
+        }
+
+        if !self.message_batches_received.is_empty() {
+            let samples = self.channel_samples(&self.message_batches_received, |info| info.to);
+            builder.add_samples("message-batches-received", "count", &samples);
+        }
+
+        if !self.errors.is_empty() {
+            let samples = self.error_samples();
+            builder.add_samples("errors", "count", &samples);
+        }
+
+        if !self.hydration.is_empty() {
+            let samples = self.hydration_samples();
+            builder.add_samples("hydration", "nanoseconds", &samples);
+        }
+
+        if !self.export_frontiers.is_empty() {
+            let (lag, input_lag) = self.lag_samples();
+            builder.add_samples("lag", "nanoseconds", &lag);
+            builder.add_samples("input-lag", "nanoseconds", &input_lag);
+        }
+
+        if !self.peeks.is_empty() {
+            let (peeks, peek_time) = self.peek_samples();
+            builder.add_samples("peeks", "count", &peeks);
+            builder.add_samples("peek-time", "nanoseconds", &peek_time);
+        }
+    }
+
+    fn add_operators<'a>(&'a self, builder: &mut ProfileBuilder<'a>) {
+        let dataflow_names = self.dataflow_names();
         for (id, info) in &self.operators {
-            builder.add_operator(*id, info);
+            let name = match *info.address.0 {
+                [dataflow] => dataflow_names.get(&dataflow).unwrap_or(&info.name),
+                _ => &info.name,
+            };
+            builder.add_operator(*id, info, name);
         }
 
-        if !self.elapsed.is_empty() {
-            let ops_by_address: BTreeMap<_, _> = self
+        match self.stacks {
+            StackMode::Operator => {}
+            StackMode::Lir => {
+                for (id, frames) in self.lir_frames() {
+                    builder.add_operator_parents(id, frames);
+                }
+            }
+            StackMode::Dependencies => {
+                for (id, paths) in self.dependency_paths() {
+                    builder.add_dataflow_parents(id, paths);
+                }
+            }
+        }
+    }
+
+    fn elapsed_samples(&self) -> BTreeMap<(OpId, WorkerId), i64> {
+        let ops_by_address: BTreeMap<_, _> = self
+            .operators
+            .iter()
+            .map(|(id, op)| (&op.address, *id))
+            .collect();
+
+        let mut elapsed_ns: BTreeMap<_, _> = self
+            .elapsed
+            .iter()
+            .map(|(key, duration)| {
+                let nanos: i64 = duration.as_nanos().try_into().unwrap();
+                (*key, nanos)
+            })
+            .collect();
+
+        // Elapsed times are cumulative, i.e. each node includes the elapsed times of its
+        // children. We need to make them non-cumulative, to match pprof's expectations.
+        for (&(id, worker), &duration) in self.elapsed.iter().rev() {
+            let parent_ns = self
                 .operators
-                .iter()
-                .map(|(id, op)| (&op.address, *id))
-                .collect();
+                .get(&id)
+                .and_then(|op| op.address.parent())
+                .and_then(|parent_addr| ops_by_address.get(&parent_addr))
+                .and_then(|parent_id| elapsed_ns.get_mut(&(*parent_id, worker)));
+
+            if let Some(parent_ns) = parent_ns {
+                let nanos = duration.as_nanos().try_into().unwrap();
+                *parent_ns = parent_ns.saturating_sub(nanos);
+            }
+        }
+
+        elapsed_ns
+    }
+
+    fn duration_samples(&self) -> BTreeMap<SampleKey, i64> {
+        let parent_addrs: BTreeSet<_> = self
+            .operators
+            .values()
+            .filter_map(|op| op.address.parent())
+            .collect();
+
+        // Like elapsed times, activation durations are cumulative. We only report leaf
+        // operators, whose activations are not already covered by their children.
+        //
+        // The histogram only records the upper bound of each duration bucket, so the
+        // reported times are an overestimate of at most 2x.
+        let mut samples = BTreeMap::new();
+        for (&(id, worker, bucket), &count) in &self.durations {
+            let is_leaf = self
+                .operators
+                .get(&id)
+                .is_some_and(|op| !parent_addrs.contains(&op.address));
+            if !is_leaf {
+                continue;
+            }
+
+            let bucket_ns = i64::try_from(bucket).unwrap_or(i64::MAX);
+            let key = SampleKey::from((id, worker)).with_label(Label::Num(
+                "duration_bucket",
+                bucket_ns,
+                "nanoseconds",
+            ));
+            samples.insert(key, count.saturating_mul(bucket_ns));
+        }
+
+        samples
+    }
+
+    fn park_samples(&self) -> BTreeMap<SampleKey, i64> {
+        // Like activation durations, park durations are only known up to their bucket's
+        // upper bound.
+        let mut samples = BTreeMap::new();
+        for (&(worker, bucket), &count) in &self.parks {
+            let bucket_ns = i64::try_from(bucket).unwrap_or(i64::MAX);
+            let key = SampleKey {
+                stack: vec![
+                    Frame::Synthetic("<parked>".into()),
+                    Frame::Synthetic(format!("<worker {worker}>")),
+                ],
+                labels: vec![Label::Str("worker", worker.to_string())],
+            };
+            let value = samples.entry(key).or_insert(0_i64);
+            *value = value.saturating_add(count.saturating_mul(bucket_ns));
+        }
+
+        samples
+    }
+
+    fn size_samples(&self) -> BTreeMap<SampleKey, i64> {
+        let mut samples = BTreeMap::new();
+        for (&(id, worker), &size) in &self.sizes {
+            let mut key = SampleKey::from((id, worker));
+            if let Some(&count) = self.sharing.get(&(id, worker)) {
+                key = key.with_label(Label::Num("sharing", count, "count"));
+            }
+            samples.insert(key, size);
+        }
+
+        samples
+    }
+
+    fn error_samples(&self) -> BTreeMap<SampleKey, i64> {
+        let roots = self.dataflow_roots();
+
+        let mut samples = BTreeMap::new();
+        for ((export, worker), &count) in &self.errors {
+            let key = SampleKey {
+                stack: self.export_stack(export, &roots),
+                labels: vec![Label::Str("worker", worker.to_string())],
+            };
+            samples.insert(key, count);
+        }
+
+        samples
+    }
+
+    fn hydration_samples(&self) -> BTreeMap<SampleKey, i64> {
+        let roots = self.dataflow_roots();
+
+        // A dataflow is hydrated once all its exports are, so we weight each dataflow by the
+        // maximum hydration time of its exports.
+        let mut samples = BTreeMap::new();
+        for (export, time) in &self.hydration {
+            let root = self
+                .exports
+                .get(export)
+                .and_then(|e| roots.get(&e.dataflow));
+            let stack = match root {
+                Some(&id) => vec![Frame::Operator(id)],
+                None => self.export_stack(export, &roots),
+            };
+            let key = SampleKey {
+                stack,
+                labels: Vec::new(),
+            };
+
+            let nanos: i64 = time.as_nanos().try_into().unwrap();
+            let value = samples.entry(key).or_insert(0);
+            *value = nanos.max(*value);
+        }
+
+        samples
+    }
 
-            let mut elapsed_ns: BTreeMap<_, _> = self
-                .elapsed
-                .iter()
-                .map(|(key, duration)| {
-                    let nanos: i64 = duration.as_nanos().try_into().unwrap();
-                    (*key, nanos)
-                })
-                .collect();
+    /// Compute export lags relative to the wall clock, and relative to their imports.
+    fn lag_samples(&self) -> (BTreeMap<SampleKey, i64>, BTreeMap<SampleKey, i64>) {
+        let roots = self.dataflow_roots();
+        let now = self.end.unwrap_or_default();
 
-            // Elapsed times are cumulative, i.e. each node includes the elapsed times of its
-            // children. We need to make them non-cumulative, to match pprof's expectations.
-            for (&(id, worker), &duration) in self.elapsed.iter().rev() {
-                let parent_ns = self
-                    .operators
-                    .get(&id)
-                    .and_then(|op| op.address.parent())
-                    .and_then(|parent_addr| ops_by_address.get(&parent_addr))
-                    .and_then(|parent_id| elapsed_ns.get_mut(&(*parent_id, worker)));
+        let frontiers: BTreeMap<_, _> = self
+            .export_frontiers
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|((export, time), _)| (export, *time))
+            .collect();
 
-                if let Some(parent_ns) = parent_ns {
-                    let nanos = duration.as_nanos().try_into().unwrap();
-                    *parent_ns = parent_ns.saturating_sub(nanos);
+        let mut lag = BTreeMap::new();
+        for (&export, &time) in &frontiers {
+            let key = SampleKey {
+                stack: self.export_stack(export, &roots),
+                labels: Vec::new(),
+            };
+            let nanos = now.saturating_sub(time).as_nanos().try_into().unwrap();
+            lag.insert(key, nanos);
+        }
+
+        let mut input_lag = BTreeMap::new();
+        for ((export, import, time), count) in &self.import_frontiers {
+            if *count <= 0 {
+                continue;
+            }
+            let Some(export_time) = frontiers.get(export) else {
+                continue;
+            };
+
+            let mut stack = self.export_stack(export, &roots);
+            stack.insert(0, Frame::Synthetic(format!("Import: {import}")));
+            let key = SampleKey {
+                stack,
+                labels: Vec::new(),
+            };
+            let nanos = time
+                .saturating_sub(*export_time)
+                .as_nanos()
+                .try_into()
+                .unwrap();
+            input_lag.insert(key, nanos);
+        }
+
+        (lag, input_lag)
+    }
+
+    fn peek_samples(&self) -> (BTreeMap<SampleKey, i64>, BTreeMap<SampleKey, i64>) {
+        let roots = self.dataflow_roots();
+        let export_key = |export| SampleKey {
+            stack: self.export_stack(export, &roots),
+            labels: Vec::new(),
+        };
+
+        let peeks = self
+            .peeks
+            .iter()
+            .map(|(export, count)| (export_key(export), *count))
+            .collect();
+        let peek_time = self
+            .peek_time
+            .iter()
+            .map(|(export, time)| {
+                let nanos = time.as_nanos().try_into().unwrap();
+                (export_key(export), nanos)
+            })
+            .collect();
+
+        (peeks, peek_time)
+    }
+
+    /// Name dataflows after the SQL objects they export, e.g. `materialize.public.idx (u1)`.
+    fn dataflow_names(&self) -> BTreeMap<DataflowId, String> {
+        let mut names = BTreeMap::<_, Vec<_>>::new();
+        for (id, export) in &self.exports {
+            if let Some(name) = &export.name {
+                names
+                    .entry(export.dataflow)
+                    .or_default()
+                    .push(format!("{name} ({id})"));
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|(dataflow, names)| (dataflow, names.join(", ")))
+            .collect()
+    }
+
+    /// Determine the LIR frames, innermost first, of all operators that belong to an LIR node.
+    fn lir_frames(&self) -> BTreeMap<OpId, Vec<Frame>> {
+        // Operator ID ranges of LIR nodes are disjoint, so we can look them up by start ID.
+        // Nodes without operators, like `Get`s of existing arrangements, have empty ranges that
+        // can share their start ID with another node, so we skip them.
+        let nodes_by_start: BTreeMap<_, _> = self
+            .lir
+            .iter()
+            .filter(|(_, info)| info.operator_ids.0 < info.operator_ids.1)
+            .map(|(key, info)| (info.operator_ids.0, (key, info)))
+            .collect();
+
+        let mut frames = BTreeMap::new();
+        for &id in self.operators.keys() {
+            let node = nodes_by_start
+                .range(..=id)
+                .next_back()
+                .filter(|(_, (_, info))| id < info.operator_ids.1);
+            let Some((_, (key, _))) = node else {
+                continue;
+            };
+
+            let (export, mut lir_id) = (*key).clone();
+            let mut stack = Vec::new();
+            while let Some(info) = self.lir.get(&(export.clone(), lir_id)) {
+                let name = format!("{lir_id}: {}", info.operator);
+                stack.push(Frame::Lir(export.clone(), lir_id, name));
+
+                match info.parent {
+                    Some(parent) => lir_id = parent,
+                    None => break,
                 }
             }
 
-            builder.add_samples("time", "nanoseconds", &elapsed_ns);
+            frames.insert(id, stack);
         }
 
-        if !self.sizes.is_empty() {
-            builder.add_samples("size", "bytes", &self.sizes);
+        frames
+    }
+
+    /// Determine the dependency paths of all dataflows, keyed by their root operators.
+    ///
+    /// Each path starts at an object exported by the dataflow and follows the object's
+    /// dependencies upstream. Objects with multiple dependencies have multiple paths.
+    fn dependency_paths(&self) -> BTreeMap<OpId, Vec<Vec<Frame>>> {
+        let roots = self.dataflow_roots();
+
+        let mut dependencies = BTreeMap::<_, Vec<_>>::new();
+        for ((id, referenced_id), count) in &self.dependencies {
+            if *count > 0 {
+                dependencies.entry(id).or_default().push(referenced_id);
+            }
+        }
+
+        let mut paths = BTreeMap::<_, BTreeSet<_>>::new();
+        for (export, info) in &self.exports {
+            if let Some(&root) = roots.get(&info.dataflow) {
+                let export_paths = self.upstream_paths(export, &dependencies);
+                paths.entry(root).or_default().extend(export_paths);
+            }
         }
 
-        builder.build()
+        paths
+            .into_iter()
+            .map(|(root, paths)| (root, paths.into_iter().collect()))
+            .collect()
+    }
+
+    fn upstream_paths(
+        &self,
+        object: &ObjectId,
+        dependencies: &BTreeMap<&ObjectId, Vec<&ObjectId>>,
+    ) -> Vec<Vec<Frame>> {
+        let name = self
+            .object_names
+            .get(object)
+            .or_else(|| self.exports.get(object).and_then(|e| e.name.as_ref()));
+        let frame = match name {
+            Some(name) => Frame::Synthetic(format!("{name} ({object})")),
+            None => Frame::Synthetic(object.clone()),
+        };
+
+        let mut paths = Vec::new();
+        for referenced_id in dependencies.get(object).into_iter().flatten() {
+            for mut path in self.upstream_paths(referenced_id, dependencies) {
+                path.insert(0, frame.clone());
+                paths.push(path);
+            }
+        }
+
+        if paths.is_empty() {
+            paths.push(vec![frame]);
+        }
+        paths
+    }
+
+    /// Return the root operators of all known dataflows.
+    fn dataflow_roots(&self) -> BTreeMap<DataflowId, OpId> {
+        self.operators
+            .iter()
+            .filter_map(|(id, op)| match *op.address.0 {
+                [dataflow] => Some((dataflow, *id)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Build a stack attributing samples to the given export, below its dataflow's root.
+    fn export_stack(&self, export: &ExportId, roots: &BTreeMap<DataflowId, OpId>) -> Vec<Frame> {
+        let mut stack = vec![Frame::Synthetic(format!("Export: {export}"))];
+
+        let root = self
+            .exports
+            .get(export)
+            .and_then(|e| roots.get(&e.dataflow));
+        if let Some(&id) = root {
+            stack.push(Frame::Operator(id));
+        }
+
+        stack
+    }
+
+    /// Attribute per-channel values to the operators at the given channel endpoint.
+    fn channel_samples(
+        &self,
+        values: &BTreeMap<(ChannelId, WorkerId), i64>,
+        endpoint: impl Fn(&ChannelInfo) -> Option<OpId>,
+    ) -> BTreeMap<(OpId, WorkerId), i64> {
+        let mut samples = BTreeMap::new();
+        for (&(channel, worker), &value) in values {
+            let op = self.channels.get(&channel).and_then(&endpoint);
+            if let Some(id) = op {
+                accumulate(&mut samples, (id, worker), value);
+            }
+        }
+        samples
+    }
+}
+
+/// Build a single profile from the given aggregators, labeling samples with their cluster and
+/// replica.
+pub fn build_pprof(aggregators: &[Aggregator]) -> pp::Profile {
+    let mut builder = ProfileBuilder::new();
+    for aggregator in aggregators {
+        aggregator.add_to_profile(&mut builder);
+    }
+    builder.build()
+}
+
+fn to_sample_keys<K: Into<SampleKey>>(samples: BTreeMap<K, i64>) -> BTreeMap<SampleKey, i64> {
+    samples.into_iter().map(|(k, v)| (k.into(), v)).collect()
+}
+
+/// Add a root-level sample accounting for the part of the `total` replica resource usage not
+/// explained by the given samples.
+fn add_overhead_sample(samples: &mut BTreeMap<SampleKey, i64>, total: i64) {
+    let explained: i64 = samples.values().sum();
+    let key = SampleKey {
+        stack: vec![Frame::Synthetic("<replica-overhead>".into())],
+        labels: Vec::new(),
+    };
+    samples.insert(key, total.saturating_sub(explained).max(0));
+}
+
+/// Add `diff` to the value stored for `key`.
+fn accumulate<K: Ord>(map: &mut BTreeMap<K, i64>, key: K, diff: i64) {
+    map.entry(key).and_modify(|x| *x += diff).or_insert(diff);
+}
+
+/// Add `diff` to the counter stored for `key`.
+///
+/// Counters are retracted when their dataflow is dropped. We ignore these retractions, to
+/// retain the counts observed up to that point.
+fn accumulate_counter<K: Ord>(map: &mut BTreeMap<K, i64>, key: K, diff: i64) {
+    if diff > 0 {
+        accumulate(map, key, diff);
+    }
+}
+
+/// Identifies a sample in the profile, by its stack and labels.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SampleKey {
+    /// The sample's stack frames, leaf first.
+    stack: Vec<Frame>,
+    labels: Vec<Label>,
+}
+
+impl SampleKey {
+    fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
     }
 }
 
+impl From<(OpId, WorkerId)> for SampleKey {
+    fn from((op, worker): (OpId, WorkerId)) -> Self {
+        Self {
+            stack: vec![Frame::Operator(op)],
+            labels: vec![Label::Str("worker", worker.to_string())],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Frame {
+    /// An operator, followed by the frames of all its ancestors.
+    Operator(OpId),
+    /// An LIR node of an export, with its display name.
+    Lir(ExportId, LirId, String),
+    /// A frame not corresponding to any operator.
+    Synthetic(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Str(&'static str, String),
+    Num(&'static str, i64, &'static str),
+}
+
 struct ProfileBuilder<'a> {
     string_table: StringTable,
-    locations: BTreeMap<OpId, pp::Location>,
-    functions: BTreeMap<OpId, pp::Function>,
+    /// Locations by scope and frame.
+    locations: BTreeMap<(usize, Frame), pp::Location>,
+    /// Functions by name, shared by all locations of the same name.
+    functions: BTreeMap<String, pp::Function>,
     sample_types: Vec<pp::ValueType>,
-    samples: BTreeMap<(OpId, WorkerId), pp::Sample>,
+    /// Samples by key. A key has multiple samples if its stack has multiple paths, which
+    /// share its values.
+    samples: BTreeMap<(usize, SampleKey), Vec<pp::Sample>>,
+    /// The current scope, e.g. a replica. Frames and samples of different scopes are distinct.
+    scope: usize,
+    /// Labels attached to all samples of the current scope.
+    scope_labels: Vec<Label>,
     op_addrs_by_id: BTreeMap<OpId, &'a Address>,
     op_ids_by_addr: BTreeMap<&'a Address, OpId>,
+    dataflow_names: BTreeMap<DataflowId, String>,
+    /// Extra frames inserted between operators and their dataflow's root.
+    op_parents: BTreeMap<OpId, Vec<Frame>>,
+    /// Extra frames placed above dataflow roots, one list per path.
+    dataflow_parents: BTreeMap<OpId, Vec<Vec<Frame>>>,
     time: Option<Duration>,
 }
 
@@ -134,8 +869,13 @@ impl<'a> ProfileBuilder<'a> {
             functions: BTreeMap::new(),
             sample_types: Vec::new(),
             samples: BTreeMap::new(),
+            scope: 0,
+            scope_labels: Vec::new(),
             op_addrs_by_id: BTreeMap::new(),
             op_ids_by_addr: BTreeMap::new(),
+            dataflow_names: BTreeMap::new(),
+            op_parents: BTreeMap::new(),
+            dataflow_parents: BTreeMap::new(),
             time: None,
         }
     }
@@ -145,80 +885,232 @@ impl<'a> ProfileBuilder<'a> {
     }
 
     fn set_time(&mut self, time: Duration) {
-        self.time = Some(time);
+        self.time = Some(self.time.map_or(time, |t| t.min(time)));
+    }
+
+    /// Start a new scope, forgetting the operators of the previous one.
+    fn begin_scope(&mut self, labels: Vec<Label>) {
+        self.scope += 1;
+        self.scope_labels = labels;
+        self.op_addrs_by_id.clear();
+        self.op_ids_by_addr.clear();
+        self.dataflow_names.clear();
+        self.op_parents.clear();
+        self.dataflow_parents.clear();
     }
 
-    fn add_operator(&mut self, id: OpId, info: &'a OpInfo) {
-        self.add_location(id, &info.name);
+    fn add_operator(&mut self, id: OpId, info: &'a OpInfo, name: &str) {
+        self.add_location(Frame::Operator(id), name);
         self.op_addrs_by_id.insert(id, &info.address);
         self.op_ids_by_addr.insert(&info.address, id);
+
+        if let [dataflow] = *info.address.0 {
+            self.dataflow_names.insert(dataflow, name.into());
+        }
     }
 
-    fn add_location(&mut self, id: OpId, name: &str) {
-        let function = pp::Function {
-            id,
-            name: self.add_string(name),
-            ..Default::default()
+    fn add_operator_parents(&mut self, id: OpId, frames: Vec<Frame>) {
+        self.op_parents.insert(id, frames);
+    }
+
+    fn add_dataflow_parents(&mut self, root: OpId, paths: Vec<Vec<Frame>>) {
+        self.dataflow_parents.insert(root, paths);
+    }
+
+    fn add_location(&mut self, frame: Frame, name: &str) -> u64 {
+        let id = u64::try_from(self.locations.len() + 1).expect("must fit");
+        let address = match frame {
+            Frame::Operator(op_id) => op_id,
+            Frame::Lir(..) | Frame::Synthetic(_) => 0,
         };
+
         let location = pp::Location {
             id,
-            address: id,
+            address,
             line: vec![pp::Line {
-                function_id: id,
+                function_id: self.function_id(name),
+                line: address.try_into().expect("must fit"),
                 ..Default::default()
             }],
             ..Default::default()
         };
 
-        self.functions.insert(id, function);
-        self.locations.insert(id, location);
+        self.locations.insert((self.scope, frame), location);
+        id
+    }
+
+    fn function_id(&mut self, name: &str) -> u64 {
+        if let Some(function) = self.functions.get(name) {
+            return function.id;
+        }
+
+        let id = u64::try_from(self.functions.len() + 1).expect("must fit");
+        let function = pp::Function {
+            id,
+            name: self.add_string(name),
+            ..Default::default()
+        };
+        self.functions.insert(name.into(), function);
+        id
     }
 
-    fn add_samples(&mut self, type_: &str, unit: &str, samples: &BTreeMap<(OpId, WorkerId), i64>) {
+    fn location_id(&mut self, frame: &Frame) -> u64 {
+        if let Some(location) = self.locations.get(&(self.scope, frame.clone())) {
+            return location.id;
+        }
+
+        let name = match frame {
+            Frame::Operator(_) => "<unknown>",
+            Frame::Lir(_, _, name) | Frame::Synthetic(name) => name,
+        };
+        self.add_location(frame.clone(), name)
+    }
+
+    fn add_samples<K>(&mut self, type_: &str, unit: &str, samples: &BTreeMap<K, i64>)
+    where
+        K: Clone + Into<SampleKey>,
+    {
         let sample_type = pp::ValueType {
             type_: self.add_string(type_),
             unit: self.add_string(unit),
             ..Default::default()
         };
 
-        self.sample_types.push(sample_type);
-        for sample in self.samples.values_mut() {
-            sample.value.push(0);
-        }
+        // Sample types are shared between scopes.
+        let existing = self.sample_types.iter().position(|t| *t == sample_type);
+        let index = existing.unwrap_or_else(|| {
+            self.sample_types.push(sample_type);
+            for sample in self.samples.values_mut().flatten() {
+                sample.value.push(0);
+            }
+            self.sample_types.len() - 1
+        });
 
         let len = self.sample_types.len();
 
-        for (&key, &value) in samples {
-            let (id, worker) = key;
+        for (key, &value) in samples {
+            let key = (self.scope, key.clone().into());
             if !self.samples.contains_key(&key) {
-                let stack = self.build_operator_stack(id);
-                let sample = pp::Sample {
-                    location_id: stack,
-                    value: vec![0; len],
-                    label: vec![pp::Label {
-                        key: self.add_string("worker"),
-                        str: self.add_string(&worker.to_string()),
+                let (_, SampleKey { stack, labels }) = &key;
+                let mut labels = labels.clone();
+                labels.extend(self.operator_labels(stack));
+                labels.extend(self.scope_labels.iter().cloned());
+                let label: Vec<_> = labels.iter().map(|l| self.build_label(l)).collect();
+                let samples = self
+                    .build_stacks(stack)
+                    .into_iter()
+                    .map(|stack| pp::Sample {
+                        location_id: stack,
+                        value: vec![0; len],
+                        label: label.clone(),
                         ..Default::default()
-                    }],
-                    ..Default::default()
-                };
-                self.samples.insert(key, sample);
+                    })
+                    .collect();
+                self.samples.insert(key.clone(), samples);
+            }
+
+            // Split the value between the key's samples, so the profile's totals stay intact.
+            let samples = self.samples.get_mut(&key).unwrap();
+            let count = i64::try_from(samples.len()).expect("must fit");
+            let (share, rest) = (value.div_euclid(count), value.rem_euclid(count));
+            for (i, sample) in (0..).zip(samples) {
+                sample.value[index] = share + i64::from(i < rest);
             }
+        }
+    }
+
+    fn build_label(&mut self, label: &Label) -> pp::Label {
+        match label {
+            Label::Str(key, value) => pp::Label {
+                key: self.add_string(key),
+                str: self.add_string(value),
+                ..Default::default()
+            },
+            Label::Num(key, value, unit) => pp::Label {
+                key: self.add_string(key),
+                num: *value,
+                num_unit: self.add_string(unit),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Determine the labels identifying the given frames' leaf operator and its dataflow.
+    fn operator_labels(&self, frames: &[Frame]) -> Vec<Label> {
+        let leaf = frames.iter().find_map(|frame| match frame {
+            Frame::Operator(id) => self.op_addrs_by_id.get(id).map(|addr| (id, addr)),
+            Frame::Lir(..) | Frame::Synthetic(_) => None,
+        });
+        let Some((id, addr)) = leaf else {
+            return Vec::new();
+        };
+
+        let dataflow = addr.0[0];
+        let mut labels = vec![
+            Label::Str("operator_id", id.to_string()),
+            Label::Str("address", addr.to_string()),
+            Label::Str("dataflow_id", dataflow.to_string()),
+        ];
+        if let Some(name) = self.dataflow_names.get(&dataflow) {
+            labels.push(Label::Str("dataflow_name", name.clone()));
+        }
+        labels
+    }
+
+    /// Build the stacks for the given frames, one for each path above their dataflow root.
+    fn build_stacks(&mut self, frames: &[Frame]) -> Vec<Vec<u64>> {
+        let stack = self.build_stack(frames);
+
+        let root = match frames.last() {
+            Some(Frame::Operator(id)) => self.dataflow_root(*id),
+            _ => None,
+        };
+        let Some(paths) = root.and_then(|id| self.dataflow_parents.get(&id)).cloned() else {
+            return vec![stack];
+        };
 
-            let sample = self.samples.get_mut(&key).unwrap();
-            sample.value[len - 1] = value;
+        paths
+            .iter()
+            .map(|path| {
+                let mut stack = stack.clone();
+                stack.extend(path.iter().map(|frame| self.location_id(frame)));
+                stack
+            })
+            .collect()
+    }
+
+    fn dataflow_root(&self, id: OpId) -> Option<OpId> {
+        let addr = self.op_addrs_by_id.get(&id)?;
+        let root = Address(addr.0[..1].into());
+        self.op_ids_by_addr.get(&root).copied()
+    }
+
+    fn build_stack(&mut self, frames: &[Frame]) -> Vec<u64> {
+        let mut stack = Vec::new();
+        for frame in frames {
+            match frame {
+                Frame::Operator(id) => stack.extend(self.build_operator_stack(*id)),
+                Frame::Lir(..) | Frame::Synthetic(_) => stack.push(self.location_id(frame)),
+            }
         }
+        stack
     }
 
-    fn build_operator_stack(&mut self, id: OpId) -> Vec<OpId> {
-        let mut stack = vec![id];
+    fn build_operator_stack(&mut self, id: OpId) -> Vec<u64> {
+        let mut stack = vec![self.location_id(&Frame::Operator(id))];
 
-        if let Some(addr) = self.op_addrs_by_id.get(&id) {
+        if let Some(&addr) = self.op_addrs_by_id.get(&id) {
+            let parents = self.op_parents.get(&id).cloned().unwrap_or_default();
             for addr in addr.ancestors() {
-                stack.push(self.op_ids_by_addr[&addr]);
+                if addr.is_dataflow_root() {
+                    for frame in &parents {
+                        stack.push(self.location_id(frame));
+                    }
+                }
+
+                let id = self.op_ids_by_addr[&addr];
+                stack.push(self.location_id(&Frame::Operator(id)));
             }
-        } else if !self.locations.contains_key(&id) {
-            self.add_location(id, "<unknown>");
         }
 
         stack
@@ -234,7 +1126,7 @@ impl<'a> ProfileBuilder<'a> {
         prof.function = self.functions.into_values().collect();
         prof.location = self.locations.into_values().collect();
         prof.sample_type = self.sample_types;
-        prof.sample = self.samples.into_values().collect();
+        prof.sample = self.samples.into_values().flatten().collect();
 
         prof.string_table = self.string_table.finish();
         prof
@@ -1,7 +1,5 @@
 pub mod subscribe;
 
-use std::any::TypeId;
-use std::collections::BTreeMap;
 use std::time::Duration;
 
 use async_stream::try_stream;
@@ -9,20 +7,55 @@ use futures::StreamExt;
 use futures::stream::BoxStream;
 use sqlx::Connection;
 use sqlx::postgres::{PgConnectOptions, PgConnection};
-use tokio_stream::{StreamMap, StreamNotifyClose};
+use tokio::sync::watch;
 
 use crate::collect::subscribe::Subscribe;
-use crate::types::{OpId, OpInfo, WorkerId};
+use crate::types::{
+    ChannelId, ChannelInfo, ExportId, ExportInfo, LirId, LirInfo, ObjectId, OpId, OpInfo, PeekId,
+    ProcessId, WorkerId,
+};
+
+/// List the cluster and replica names of all replicas of the given cluster, or of all user
+/// clusters if none is given.
+pub async fn list_replicas(
+    sql_url: &str,
+    cluster: Option<&str>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let connect_options = sql_url
+        .parse::<PgConnectOptions>()?
+        .application_name("mzprof");
+    let mut conn = PgConnection::connect_with(&connect_options).await?;
+
+    let replicas = sqlx::query_as(
+        "
+        SELECT c.name, r.name
+        FROM mz_catalog.mz_cluster_replicas r
+        JOIN mz_catalog.mz_clusters c ON c.id = r.cluster_id
+        WHERE CASE WHEN $1::text IS NULL THEN c.id LIKE 'u%' ELSE c.name = $1 END
+        ORDER BY c.name, r.name
+        ",
+    )
+    .bind(cluster)
+    .fetch_all(&mut conn)
+    .await?;
+
+    Ok(replicas)
+}
 
+/// Collects the data of multiple specs through a single subscription and connection.
 pub struct Collector {
     connect_options: PgConnectOptions,
-    stream: StreamMap<TypeId, StreamNotifyClose<Subscribe>>,
-    progress: BTreeMap<TypeId, Duration>,
-    stash: BTreeMap<Duration, Vec<Update>>,
+    mode: subscribe::Mode,
+    specs: Vec<Box<dyn subscribe::Spec>>,
 }
 
 impl Collector {
-    pub fn new(sql_url: &str, cluster: &str, replica: &str) -> anyhow::Result<Self> {
+    pub fn new(
+        sql_url: &str,
+        cluster: &str,
+        replica: &str,
+        mode: subscribe::Mode,
+    ) -> anyhow::Result<Self> {
         let connect_options = sql_url
             .parse::<PgConnectOptions>()?
             .application_name("mzprof")
@@ -30,79 +63,155 @@ impl Collector {
 
         Ok(Self {
             connect_options,
-            stream: StreamMap::new(),
-            progress: BTreeMap::new(),
-            stash: BTreeMap::new(),
+            mode,
+            specs: Vec::new(),
         })
     }
 
-    async fn connect(&self) -> anyhow::Result<PgConnection> {
-        let conn = PgConnection::connect_with(&self.connect_options).await?;
-        Ok(conn)
+    pub fn subscribe(&mut self, spec: impl subscribe::Spec) {
+        self.specs.push(Box::new(spec));
     }
 
-    pub async fn subscribe(
-        &mut self,
-        spec: impl subscribe::Spec,
-        mode: subscribe::Mode,
-    ) -> anyhow::Result<()> {
-        let id = spec.type_id();
-        let conn = self.connect().await?;
-        let sub = Subscribe::start(conn, spec, mode);
-        let stream = StreamNotifyClose::new(sub);
-
-        self.stream.insert(id, stream);
-        self.progress.insert(id, Duration::ZERO);
-        Ok(())
-    }
+    /// Turn the collector into a stream of batches.
+    ///
+    /// When `shutdown` changes, the collector stops subscribing and flushes all stashed
+    /// updates, including those not yet complete.
+    pub fn into_stream(
+        self,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> BoxStream<'static, anyhow::Result<Batch>> {
+        let Self {
+            connect_options,
+            mode,
+            specs,
+        } = self;
 
-    fn frontier(&self) -> Option<Duration> {
-        self.progress.values().copied().min()
-    }
+        try_stream! {
+            let connected = tokio::select! {
+                result = PgConnection::connect_with(&connect_options) => Some(result),
+                _ = shutdown.changed() => None,
+            };
+            let Some(conn) = connected else {
+                return;
+            };
+            let mut sub = Subscribe::start(conn?, specs, mode);
+            let mut progress = Duration::ZERO;
+
+            loop {
+                let next = tokio::select! {
+                    next = sub.next() => next,
+                    _ = shutdown.changed() => None,
+                };
+                let Some(result) = next else {
+                    break;
+                };
 
-    fn absorb_batch(&mut self, id: TypeId, batch: Batch) -> Vec<Batch> {
-        self.progress.insert(id, batch.time);
+                let batch = match result {
+                    Ok(batch) => batch,
+                    Err(error) => {
+                        let resumed = tokio::select! {
+                            result = resume(&connect_options, &mut sub, progress, error) => {
+                                Some(result)
+                            }
+                            _ = shutdown.changed() => None,
+                        };
+                        let Some(result) = resumed else {
+                            break;
+                        };
+                        result?;
+                        continue;
+                    }
+                };
 
-        for update in batch.updates {
-            self.stash.entry(update.time).or_default().push(update);
-        }
+                progress = batch.time;
+                yield batch;
+            }
 
-        let frontier = self.frontier().unwrap();
-        let mut batches = Vec::new();
-        while let Some((time, _)) = self.stash.first_key_value()
-            && *time < frontier
-        {
-            let (time, updates) = self.stash.pop_first().unwrap();
-            batches.push(Batch { time, updates });
+            for batch in sub.flush() {
+                yield batch;
+            }
         }
-
-        batches
+        .boxed()
     }
+}
 
-    pub fn into_stream(mut self) -> BoxStream<'static, anyhow::Result<Batch>> {
-        try_stream! {
-            while let Some((id, result)) = self.stream.next().await {
-                let Some(result) = result else {
-                    self.progress.remove(&id);
-                    continue;
-                };
+/// Resume a failed subscription, reconnecting with backoff.
+///
+/// The subscription restarts as of at least its last progressed time. Only transient
+/// failures are retried, other errors are returned.
+async fn resume(
+    connect_options: &PgConnectOptions,
+    sub: &mut Subscribe,
+    as_of: Duration,
+    mut error: anyhow::Error,
+) -> anyhow::Result<()> {
+    if !sub.is_resumable() {
+        return Err(error);
+    }
 
-                let batch = result?;
-                let ready = self.absorb_batch(id, batch);
-                for batch in ready {
-                    yield batch;
-                }
+    loop {
+        let Some(backoff) = is_transient(&error).then(|| sub.backoff()).flatten() else {
+            return Err(error);
+        };
+        println!("* subscription failed, reconnecting in {backoff:?}: {error:#}");
+        tokio::time::sleep(backoff).await;
+
+        match PgConnection::connect_with(connect_options).await {
+            Ok(conn) => {
+                sub.resume(conn, as_of);
+                return Ok(());
             }
+            Err(e) => error = e.into(),
         }
-        .boxed()
     }
 }
 
-#[derive(Clone, Debug)]
+/// Whether a subscription failed for a reason that might not persist.
+///
+/// Besides connection failures, Materialize ends a subscription with an error when its target
+/// replica fails or restarts. Other errors reported by Materialize, e.g. about the query, and
+/// errors while parsing rows are permanent.
+fn is_transient(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Io(_) | sqlx::Error::Tls(_)) => true,
+        Some(sqlx::Error::Database(e)) => e.message().contains("target replica failed"),
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Data {
     Operator(OpId, OpInfo),
+    Channel(ChannelId, ChannelInfo),
+    Export(ExportId, ExportInfo),
+    Lir(ExportId, LirId, LirInfo),
+    Dependency(ObjectId, ObjectId, String),
     Elapsed(OpId, WorkerId),
+    Schedules(OpId, WorkerId),
+    Durations(OpId, WorkerId, u64),
+    Parks(WorkerId, u64),
     Size(OpId, WorkerId),
+    Sharing(OpId, WorkerId),
+    Capacity(OpId, WorkerId),
+    Allocations(OpId, WorkerId),
+    Records(OpId, WorkerId),
+    Batches(OpId, WorkerId),
+    MessagesSent(ChannelId, WorkerId),
+    MessagesReceived(ChannelId, WorkerId),
+    MessageBatchesSent(ChannelId, WorkerId),
+    MessageBatchesReceived(ChannelId, WorkerId),
+    ErrorCounts(ExportId, WorkerId, i64),
+    Hydration(ExportId, Duration),
+    ExportFrontier(ExportId, Duration),
+    ImportFrontier(ExportId, ExportId, Duration),
+    Peek(PeekId, ExportId),
+    ReplicaMetrics(ProcessId, ReplicaMetrics),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReplicaMetrics {
+    pub cpu_nano_cores: u64,
+    pub memory_bytes: u64,
 }
 
 #[derive(Clone, Debug)]
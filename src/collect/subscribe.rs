@@ -1,8 +1,8 @@
-use std::any::Any;
-use std::mem;
+use std::collections::BTreeMap;
 use std::pin::Pin;
 use std::task::{Context, Poll, ready};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use std::{io, mem};
 
 use async_stream::try_stream;
 use futures::stream::BoxStream;
@@ -11,9 +11,9 @@ use sqlx::Row;
 use sqlx::postgres::{PgConnection, PgRow};
 use sqlx::types::Decimal;
 
-use crate::types::{Address, OpInfo};
+use crate::types::{Address, ChannelInfo, ExportInfo, LirInfo, OpInfo};
 
-use super::{Batch, Data, Update};
+use super::{Batch, Data, ReplicaMetrics as Metrics, Update};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Mode {
@@ -21,68 +21,179 @@ pub enum Mode {
     Continual { duration: Option<Duration> },
 }
 
+/// Number of consecutive failures after which a subscription is not resumed anymore.
+const MAX_FAILURES: u32 = 10;
+
+/// A single `SUBSCRIBE` over the tagged union of the queries of multiple specs.
 pub(super) struct Subscribe {
     stream: Option<BoxStream<'static, sqlx::Result<PgRow>>>,
-    spec: Box<dyn Spec>,
+    specs: Vec<Box<dyn Spec>>,
     mode: Mode,
-    stash: Vec<Update>,
+    /// Updates received since the last progress row, with the index of their spec.
+    stash: Vec<(usize, Update)>,
     up_to: Option<Duration>,
+    /// As-of time of the current `SUBSCRIBE`, known after its first row.
+    as_of: Option<Duration>,
+    /// Snapshot rows received so far, until the first progress row after the as-of time.
+    snapshot: Option<BTreeMap<(usize, Data), i64>>,
+    /// Consolidated contents of the subscribed relations, as of the last emitted batch.
+    contents: BTreeMap<(usize, Data), i64>,
+    /// Whether the first snapshot was reconciled into `contents`, after which the snapshots
+    /// of resumed subscriptions only contribute their changes.
+    seeded: bool,
+    /// Number of consecutive failures.
+    failures: u32,
 }
 
 impl Subscribe {
-    pub fn start(mut conn: PgConnection, spec: impl Spec, mode: Mode) -> Self {
-        let query = spec.subscribe_query(mode);
-        let stream = try_stream! {
-            let mut fetch = sqlx::query(&query).fetch(&mut conn);
-            while let Some(row) = fetch.try_next().await? {
-                yield row;
-            }
-        }
-        .boxed();
-
+    pub fn start(conn: PgConnection, specs: Vec<Box<dyn Spec>>, mode: Mode) -> Self {
+        let query = subscribe_query(&specs, None);
         Self {
-            stream: Some(stream),
-            spec: Box::new(spec),
+            stream: Some(fetch(conn, query)),
+            specs,
             mode,
             stash: Vec::new(),
             up_to: None,
+            as_of: None,
+            snapshot: None,
+            contents: BTreeMap::new(),
+            seeded: false,
+            failures: 0,
         }
     }
 
+    /// Whether the subscription can be resumed after a failure.
+    ///
+    /// Failures that occur before the subscription was established are likely to persist.
+    /// Snapshots can't be resumed, since their as-of time might not be readable anymore.
+    pub fn is_resumable(&self) -> bool {
+        self.up_to.is_some() && self.mode != Mode::Snapshot
+    }
+
+    /// Return the delay before the next reconnection attempt, and count a failure.
+    ///
+    /// The delay doubles with each consecutive failure, up to about a minute. Returns `None`
+    /// after too many consecutive failures, or once the wall clock passed the subscription's
+    /// end, since a resumed subscription wouldn't collect anything then.
+    pub fn backoff(&mut self) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        if self.failures >= MAX_FAILURES || self.up_to.is_none_or(|up_to| now > up_to) {
+            return None;
+        }
+
+        let backoff = Duration::from_secs(1 << self.failures.min(6));
+        self.failures += 1;
+        Some(backoff)
+    }
+
+    /// Resume the subscription on a new connection, as of at least the given time.
+    ///
+    /// The snapshot of the resumed subscription is reconciled with the contents observed so
+    /// far, so only the changes since then are emitted. If the initial snapshot wasn't
+    /// complete yet, the resumed one is treated as the initial snapshot instead.
+    pub fn resume(&mut self, conn: PgConnection, as_of: Duration) {
+        let query = subscribe_query(&self.specs, Some(as_of));
+        self.stream = Some(fetch(conn, query));
+        self.restart();
+    }
+
+    /// Discard the state of the current `SUBSCRIBE`, keeping the contents observed so far.
+    fn restart(&mut self) {
+        self.stash.clear();
+        self.as_of = None;
+        self.snapshot = None;
+    }
+
+    /// Take all stashed updates, including those not yet complete, as batches by time.
+    pub fn flush(&mut self) -> Vec<Batch> {
+        let mut updates = BTreeMap::<_, Vec<_>>::new();
+        for (_, update) in mem::take(&mut self.stash) {
+            updates.entry(update.time).or_default().push(update);
+        }
+
+        updates
+            .into_iter()
+            .map(|(time, updates)| Batch { time, updates })
+            .collect()
+    }
+
     fn absorb_row(&mut self, row: &PgRow) -> anyhow::Result<Option<Batch>> {
         let progress: bool = row.get("mz_progressed");
         let time = get_mz_timestamp(row)?;
 
         // The first row communicates the as-of time.
-        let Some(up_to) = self.up_to else {
+        let Some(as_of) = self.as_of else {
             assert!(progress);
-            let up_to = match self.mode {
-                Mode::Snapshot => time,
-                Mode::Continual { duration: Some(d) } => time + d,
-                Mode::Continual { duration: None } => Duration::MAX,
-            };
-            self.up_to = Some(up_to);
+            if self.up_to.is_none() {
+                let up_to = match self.mode {
+                    Mode::Snapshot => time,
+                    Mode::Continual { duration: Some(d) } => time + d,
+                    Mode::Continual { duration: None } => Duration::MAX,
+                };
+                self.up_to = Some(up_to);
+            }
+            self.as_of = Some(time);
+            self.snapshot = Some(BTreeMap::new());
             return Ok(None);
         };
+        let up_to = self.up_to.unwrap();
 
         if progress {
             if time > up_to {
                 self.stream = None;
             }
 
-            Ok(Some(Batch {
-                time,
-                updates: mem::take(&mut self.stash),
-            }))
+            let mut updates = match self.snapshot.take() {
+                Some(snapshot) => self.reconcile(snapshot, as_of),
+                None => Vec::new(),
+            };
+            for (index, update) in mem::take(&mut self.stash) {
+                let key = (index, update.data.clone());
+                *self.contents.entry(key).or_default() += update.diff;
+                updates.push(update);
+            }
+            self.contents.retain(|_, diff| *diff != 0);
+            self.failures = 0;
+
+            Ok(Some(Batch { time, updates }))
         } else {
-            if time <= up_to {
-                let update = self.spec.parse_update(row)?;
-                self.stash.push(update);
+            let index: usize = row.get::<i32, _>("spec").try_into()?;
+            let update = self.specs[index].parse_update(row)?;
+            if let Some(snapshot) = &mut self.snapshot {
+                *snapshot.entry((index, update.data)).or_default() += update.diff;
+            } else if time <= up_to {
+                self.stash.push((index, update));
             }
 
             Ok(None)
         }
     }
+
+    /// Replace the observed contents with the given snapshot, returning the changes between
+    /// them.
+    fn reconcile(&mut self, snapshot: BTreeMap<(usize, Data), i64>, time: Duration) -> Vec<Update> {
+        let mut changes = snapshot.clone();
+        for (key, diff) in &self.contents {
+            *changes.entry(key.clone()).or_default() -= diff;
+        }
+        self.contents = snapshot;
+        let seeded = mem::replace(&mut self.seeded, true);
+
+        if time > self.up_to.unwrap() {
+            return Vec::new();
+        }
+
+        changes
+            .into_iter()
+            .filter(|((index, _), diff)| {
+                let wanted = seeded || self.specs[*index].snapshot(self.mode);
+                wanted && *diff != 0
+            })
+            .map(|((_, data), diff)| Update { data, time, diff })
+            .collect()
+    }
 }
 
 impl Stream for Subscribe {
@@ -94,10 +205,22 @@ impl Stream for Subscribe {
                 return Poll::Ready(None);
             };
 
-            let result = ready!(stream.poll_next_unpin(cx)).unwrap();
-            let result = match result {
-                Ok(row) => self.absorb_row(&row),
-                Err(error) => Err(error.into()),
+            // The subscription only ends once it progressed past `up_to`, at which point the
+            // stream is dropped, so an earlier end means the connection was closed.
+            let result = match ready!(stream.poll_next_unpin(cx)) {
+                Some(Ok(row)) => self.absorb_row(&row),
+                Some(Err(error)) => {
+                    self.stream = None;
+                    Err(error.into())
+                }
+                None => {
+                    self.stream = None;
+                    let error = io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "subscription ended unexpectedly",
+                    );
+                    Err(sqlx::Error::Io(error).into())
+                }
             };
 
             match result {
@@ -109,14 +232,88 @@ impl Stream for Subscribe {
     }
 }
 
+/// Build a `SUBSCRIBE` over the union of the queries of the given specs.
+///
+/// Each spec's rows are tagged with its index in the `spec` column. The union has one column
+/// per distinct column name of the specs, which is `NULL` for specs that don't declare it.
+fn subscribe_query(specs: &[Box<dyn Spec>], as_of: Option<Duration>) -> String {
+    let mut columns = BTreeMap::new();
+    for (name, type_) in specs.iter().flat_map(|spec| spec.columns()) {
+        let prev = columns.insert(*name, *type_);
+        assert!(
+            prev.is_none_or(|prev| prev == *type_),
+            "conflicting types for column `{name}`",
+        );
+    }
+
+    let selects: Vec<_> = specs
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let values: Vec<_> = columns
+                .iter()
+                .map(|(name, type_)| {
+                    if spec.columns().iter().any(|(n, _)| n == name) {
+                        format!("\"{name}\"::{type_} AS \"{name}\"")
+                    } else {
+                        format!("NULL::{type_} AS \"{name}\"")
+                    }
+                })
+                .collect();
+            format!(
+                "SELECT {index}::int4 AS spec, {} {}",
+                values.join(", "),
+                spec.relation(),
+            )
+        })
+        .collect();
+
+    let query = format!(
+        "SUBSCRIBE ({}) WITH (PROGRESS)",
+        selects.join(" UNION ALL ")
+    );
+    match as_of {
+        Some(as_of) => format!("{query} AS OF AT LEAST {}", as_of.as_millis()),
+        None => query,
+    }
+}
+
+fn fetch(mut conn: PgConnection, query: String) -> BoxStream<'static, sqlx::Result<PgRow>> {
+    try_stream! {
+        let mut fetch = sqlx::query(&query).fetch(&mut conn);
+        while let Some(row) = fetch.try_next().await? {
+            yield row;
+        }
+    }
+    .boxed()
+}
+
+/// Quote a string for use as a SQL string literal.
+fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
 fn get_mz_timestamp(row: &PgRow) -> anyhow::Result<Duration> {
     let ts: Decimal = row.get("mz_timestamp");
     let ms: u64 = ts.try_into()?;
     Ok(Duration::from_millis(ms))
 }
 
-pub trait Spec: Any + Send + 'static {
-    fn query(&self) -> String;
+/// Get a frontier time, selected as `text` since sqlx cannot decode `mz_timestamp`.
+fn get_frontier_time(row: &PgRow) -> anyhow::Result<Duration> {
+    let ts: &str = row.get("time");
+    let ms: u64 = ts.parse()?;
+    Ok(Duration::from_millis(ms))
+}
+
+pub trait Spec: Send + 'static {
+    /// The relation queried by the spec, as a `FROM` clause including any filters.
+    ///
+    /// The select list is derived from `columns`, so the relation must provide a column of each
+    /// of their names.
+    fn relation(&self) -> String;
+    /// Names and SQL types of the columns selected from `relation`.
+    fn columns(&self) -> &'static [(&'static str, &'static str)];
     fn parse(&self, row: &PgRow) -> anyhow::Result<Data>;
 
     fn parse_update(&self, row: &PgRow) -> anyhow::Result<Update> {
@@ -127,23 +324,31 @@ pub trait Spec: Any + Send + 'static {
         Ok(Update { data, time, diff })
     }
 
-    fn subscribe_query(&self, _mode: Mode) -> String {
-        format!("SUBSCRIBE ({}) WITH (PROGRESS)", self.query())
+    /// Whether the subscription should start with a snapshot of the current state.
+    ///
+    /// Specs over cumulative counters skip the snapshot in continual mode, so only the
+    /// increments observed while profiling are collected. The snapshot is still fetched, to
+    /// reconcile with when the subscription is resumed.
+    fn snapshot(&self, _mode: Mode) -> bool {
+        true
     }
 }
 
 pub struct Operator;
 
 impl Spec for Operator {
-    fn query(&self) -> String {
+    fn relation(&self) -> String {
         "
-        SELECT id::int8, name, address::text
         FROM mz_introspection.mz_dataflow_operators
         JOIN mz_introspection.mz_dataflow_addresses USING (id)
         "
         .into()
     }
 
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("id", "int8"), ("name", "text"), ("address", "text")]
+    }
+
     fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
         let id = row.get::<i64, _>("id").try_into()?;
         let name = row.get("name");
@@ -165,53 +370,860 @@ impl Spec for Operator {
     }
 }
 
+pub struct Channel;
+
+impl Spec for Channel {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_dataflow_channel_operators
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("id", "int8"),
+            ("from_operator_id", "int8"),
+            ("to_operator_id", "int8"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("id").try_into()?;
+        let from = row
+            .get::<Option<i64>, _>("from_operator_id")
+            .map(TryInto::try_into)
+            .transpose()?;
+        let to = row
+            .get::<Option<i64>, _>("to_operator_id")
+            .map(TryInto::try_into)
+            .transpose()?;
+
+        let info = ChannelInfo { from, to };
+        Ok(Data::Channel(id, info))
+    }
+}
+
+pub struct Export;
+
+impl Spec for Export {
+    fn relation(&self) -> String {
+        "
+        FROM (
+            SELECT
+                e.export_id,
+                e.dataflow_id,
+                CASE WHEN o.id IS NOT NULL THEN concat_ws('.', d.name, s.name, o.name) END AS name
+            FROM mz_introspection.mz_compute_exports e
+            LEFT JOIN mz_internal.mz_object_global_ids g ON g.global_id = e.export_id
+            LEFT JOIN mz_catalog.mz_objects o ON o.id = g.id
+            LEFT JOIN mz_catalog.mz_schemas s ON s.id = o.schema_id
+            LEFT JOIN mz_catalog.mz_databases d ON d.id = s.database_id
+        ) e
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("export_id", "text"),
+            ("dataflow_id", "int8"),
+            ("name", "text"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("export_id");
+        let dataflow = row.get::<i64, _>("dataflow_id").try_into()?;
+        let name = row.get("name");
+
+        let info = ExportInfo { dataflow, name };
+        Ok(Data::Export(id, info))
+    }
+}
+
+pub struct LirMapping;
+
+impl Spec for LirMapping {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_lir_mapping
+        WHERE operator_id_start IS NOT NULL AND operator_id_end IS NOT NULL
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("global_id", "text"),
+            ("lir_id", "int8"),
+            ("operator", "text"),
+            ("parent_lir_id", "int8"),
+            ("operator_id_start", "int8"),
+            ("operator_id_end", "int8"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let global_id = row.get("global_id");
+        let lir_id = row.get::<i64, _>("lir_id").try_into()?;
+        let operator = row.get("operator");
+        let parent = row
+            .get::<Option<i64>, _>("parent_lir_id")
+            .map(TryInto::try_into)
+            .transpose()?;
+        let start = row.get::<i64, _>("operator_id_start").try_into()?;
+        let end = row.get::<i64, _>("operator_id_end").try_into()?;
+
+        let info = LirInfo {
+            operator,
+            parent,
+            operator_ids: (start, end),
+        };
+        Ok(Data::Lir(global_id, lir_id, info))
+    }
+}
+
+pub struct ObjectDependencies;
+
+impl Spec for ObjectDependencies {
+    fn relation(&self) -> String {
+        "
+        FROM (
+            SELECT
+                g.global_id AS object_id,
+                rg.global_id AS referenced_object_id,
+                concat_ws('.', d.name, s.name, o.name) AS referenced_name
+            FROM mz_internal.mz_object_dependencies dep
+            JOIN mz_internal.mz_object_global_ids g ON g.id = dep.object_id
+            JOIN mz_internal.mz_object_global_ids rg ON rg.id = dep.referenced_object_id
+            JOIN mz_catalog.mz_objects o ON o.id = dep.referenced_object_id
+            LEFT JOIN mz_catalog.mz_schemas s ON s.id = o.schema_id
+            LEFT JOIN mz_catalog.mz_databases d ON d.id = s.database_id
+            WHERE o.type IN ('table', 'source', 'view', 'materialized-view')
+        ) dep
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("object_id", "text"),
+            ("referenced_object_id", "text"),
+            ("referenced_name", "text"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("object_id");
+        let referenced_id = row.get("referenced_object_id");
+        let referenced_name = row.get("referenced_name");
+        Ok(Data::Dependency(id, referenced_id, referenced_name))
+    }
+}
+
 pub struct Elapsed;
 
 impl Spec for Elapsed {
-    fn query(&self) -> String {
+    fn relation(&self) -> String {
         "
-        SELECT id::int8, worker_id::int8
         FROM mz_introspection.mz_scheduling_elapsed_raw
         "
         .into()
     }
 
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("id", "int8"), ("worker_id", "int8")]
+    }
+
     fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
         let id = row.get::<i64, _>("id").try_into()?;
         let worker_id = row.get::<i64, _>("worker_id").try_into()?;
         Ok(Data::Elapsed(id, worker_id))
     }
 
-    fn subscribe_query(&self, mode: Mode) -> String {
-        let snapshot = match mode {
-            Mode::Snapshot => "true",
-            Mode::Continual { .. } => "false",
-        };
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
 
-        format!(
-            "SUBSCRIBE ({}) WITH (PROGRESS, SNAPSHOT = {snapshot})",
-            self.query(),
-        )
+pub struct Schedules;
+
+impl Spec for Schedules {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_compute_operator_durations_histogram_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Schedules(id, worker_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct Durations;
+
+impl Spec for Durations {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_compute_operator_durations_histogram_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("id", "int8"),
+            ("worker_id", "int8"),
+            ("duration_ns", "int8"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        let duration_ns = row.get::<i64, _>("duration_ns").try_into()?;
+        Ok(Data::Durations(id, worker_id, duration_ns))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct Parks;
+
+impl Spec for Parks {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_scheduling_parks_histogram_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("worker_id", "int8"), ("slept_for_ns", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        let slept_for_ns = row.get::<i64, _>("slept_for_ns").try_into()?;
+        Ok(Data::Parks(worker_id, slept_for_ns))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
     }
 }
 
 pub struct Size;
 
 impl Spec for Size {
-    fn query(&self) -> String {
+    fn relation(&self) -> String {
         "
-        SELECT operator_id::int8, worker_id::int8
-        FROM mz_introspection.mz_arrangement_heap_size_raw
-        UNION ALL
-        SELECT operator_id::int8, worker_id::int8
-        FROM mz_introspection.mz_arrangement_batcher_size_raw
+        FROM (
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_heap_size_raw
+            UNION ALL
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_batcher_size_raw
+        ) a
         "
         .into()
     }
 
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
     fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
         let id = row.get::<i64, _>("operator_id").try_into()?;
         let worker_id = row.get::<i64, _>("worker_id").try_into()?;
         Ok(Data::Size(id, worker_id))
     }
 }
+
+pub struct Sharing;
+
+impl Spec for Sharing {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_arrangement_sharing_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("operator_id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Sharing(id, worker_id))
+    }
+}
+
+pub struct Capacity;
+
+impl Spec for Capacity {
+    fn relation(&self) -> String {
+        "
+        FROM (
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_heap_capacity_raw
+            UNION ALL
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_batcher_capacity_raw
+        ) a
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("operator_id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Capacity(id, worker_id))
+    }
+}
+
+pub struct Allocations;
+
+impl Spec for Allocations {
+    fn relation(&self) -> String {
+        "
+        FROM (
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_heap_allocations_raw
+            UNION ALL
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_batcher_allocations_raw
+        ) a
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("operator_id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Allocations(id, worker_id))
+    }
+}
+
+pub struct Records;
+
+impl Spec for Records {
+    fn relation(&self) -> String {
+        "
+        FROM (
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_records_raw
+            UNION ALL
+            SELECT operator_id, worker_id
+            FROM mz_introspection.mz_arrangement_batcher_records_raw
+        ) a
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("operator_id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Records(id, worker_id))
+    }
+}
+
+pub struct Batches;
+
+impl Spec for Batches {
+    fn relation(&self) -> String {
+        // Batchers don't report batch counts, so only spines contribute here.
+        "
+        FROM mz_introspection.mz_arrangement_batches_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("operator_id", "int8"), ("worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("operator_id").try_into()?;
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        Ok(Data::Batches(id, worker_id))
+    }
+}
+
+pub struct MessagesSent;
+
+impl Spec for MessagesSent {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_message_counts_sent_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("channel_id", "int8"), ("from_worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("channel_id").try_into()?;
+        let worker_id = row.get::<i64, _>("from_worker_id").try_into()?;
+        Ok(Data::MessagesSent(id, worker_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct MessagesReceived;
+
+impl Spec for MessagesReceived {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_message_counts_received_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("channel_id", "int8"), ("to_worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("channel_id").try_into()?;
+        let worker_id = row.get::<i64, _>("to_worker_id").try_into()?;
+        Ok(Data::MessagesReceived(id, worker_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct MessageBatchesSent;
+
+impl Spec for MessageBatchesSent {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_message_batch_counts_sent_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("channel_id", "int8"), ("from_worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("channel_id").try_into()?;
+        let worker_id = row.get::<i64, _>("from_worker_id").try_into()?;
+        Ok(Data::MessageBatchesSent(id, worker_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct MessageBatchesReceived;
+
+impl Spec for MessageBatchesReceived {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_message_batch_counts_received_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("channel_id", "int8"), ("to_worker_id", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("channel_id").try_into()?;
+        let worker_id = row.get::<i64, _>("to_worker_id").try_into()?;
+        Ok(Data::MessageBatchesReceived(id, worker_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct ErrorCounts;
+
+impl Spec for ErrorCounts {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_compute_error_counts_raw
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("export_id", "text"),
+            ("worker_id", "int8"),
+            ("count", "int8"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("export_id");
+        let worker_id = row.get::<i64, _>("worker_id").try_into()?;
+        let count = row.get("count");
+        Ok(Data::ErrorCounts(id, worker_id, count))
+    }
+}
+
+pub struct Hydration {
+    cluster: String,
+    replica: String,
+}
+
+impl Hydration {
+    pub fn new(cluster: &str, replica: &str) -> Self {
+        Self {
+            cluster: cluster.into(),
+            replica: replica.into(),
+        }
+    }
+}
+
+impl Spec for Hydration {
+    fn relation(&self) -> String {
+        // Hydration times are reported by the controller, not the replica, so we have to
+        // select the target replica explicitly.
+        format!(
+            "
+            FROM mz_internal.mz_compute_hydration_times h
+            JOIN mz_catalog.mz_cluster_replicas r ON r.id = h.replica_id
+            JOIN mz_catalog.mz_clusters c ON c.id = r.cluster_id
+            WHERE c.name = {} AND r.name = {} AND h.time_ns IS NOT NULL
+            ",
+            quote_literal(&self.cluster),
+            quote_literal(&self.replica),
+        )
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("object_id", "text"), ("time_ns", "int8")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("object_id");
+        let nanos = row.get::<i64, _>("time_ns").try_into()?;
+        Ok(Data::Hydration(id, Duration::from_nanos(nanos)))
+    }
+}
+
+pub struct ExportFrontiers;
+
+impl Spec for ExportFrontiers {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_compute_frontiers
+        WHERE time IS NOT NULL
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("export_id", "text"), ("time", "text")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("export_id");
+        let time = get_frontier_time(row)?;
+        Ok(Data::ExportFrontier(id, time))
+    }
+}
+
+pub struct ImportFrontiers;
+
+impl Spec for ImportFrontiers {
+    fn relation(&self) -> String {
+        "
+        FROM mz_introspection.mz_compute_import_frontiers
+        WHERE time IS NOT NULL
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("export_id", "text"),
+            ("import_id", "text"),
+            ("time", "text"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("export_id");
+        let import_id = row.get("import_id");
+        let time = get_frontier_time(row)?;
+        Ok(Data::ImportFrontier(id, import_id, time))
+    }
+}
+
+pub struct Peeks;
+
+impl Spec for Peeks {
+    fn relation(&self) -> String {
+        "
+        FROM (SELECT id AS peek_id, object_id FROM mz_introspection.mz_active_peeks) p
+        "
+        .into()
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[("peek_id", "text"), ("object_id", "text")]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get("peek_id");
+        let object_id = row.get("object_id");
+        Ok(Data::Peek(id, object_id))
+    }
+
+    fn snapshot(&self, mode: Mode) -> bool {
+        mode == Mode::Snapshot
+    }
+}
+
+pub struct ReplicaMetrics {
+    cluster: String,
+    replica: String,
+}
+
+impl ReplicaMetrics {
+    pub fn new(cluster: &str, replica: &str) -> Self {
+        Self {
+            cluster: cluster.into(),
+            replica: replica.into(),
+        }
+    }
+}
+
+impl Spec for ReplicaMetrics {
+    fn relation(&self) -> String {
+        format!(
+            "
+            FROM mz_internal.mz_cluster_replica_metrics m
+            JOIN mz_catalog.mz_cluster_replicas r ON r.id = m.replica_id
+            JOIN mz_catalog.mz_clusters c ON c.id = r.cluster_id
+            WHERE c.name = {} AND r.name = {}
+            ",
+            quote_literal(&self.cluster),
+            quote_literal(&self.replica),
+        )
+    }
+
+    fn columns(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("process_id", "int8"),
+            ("cpu_nano_cores", "int8"),
+            ("memory_bytes", "int8"),
+        ]
+    }
+
+    fn parse(&self, row: &PgRow) -> anyhow::Result<Data> {
+        let id = row.get::<i64, _>("process_id").try_into()?;
+        let cpu_nano_cores = row.get::<Option<i64>, _>("cpu_nano_cores");
+        let memory_bytes = row.get::<Option<i64>, _>("memory_bytes");
+
+        let metrics = Metrics {
+            cpu_nano_cores: cpu_nano_cores.unwrap_or(0).try_into()?,
+            memory_bytes: memory_bytes.unwrap_or(0).try_into()?,
+        };
+        Ok(Data::ReplicaMetrics(id, metrics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_specs() -> Vec<Box<dyn Spec>> {
+        vec![
+            Box::new(Operator),
+            Box::new(Channel),
+            Box::new(Export),
+            Box::new(LirMapping),
+            Box::new(ObjectDependencies),
+            Box::new(Elapsed),
+            Box::new(Schedules),
+            Box::new(Durations),
+            Box::new(Parks),
+            Box::new(Size),
+            Box::new(Sharing),
+            Box::new(Capacity),
+            Box::new(Allocations),
+            Box::new(Records),
+            Box::new(Batches),
+            Box::new(MessagesSent),
+            Box::new(MessagesReceived),
+            Box::new(MessageBatchesSent),
+            Box::new(MessageBatchesReceived),
+            Box::new(ErrorCounts),
+            Box::new(Hydration::new("c", "r")),
+            Box::new(ExportFrontiers),
+            Box::new(ImportFrontiers),
+            Box::new(Peeks),
+            Box::new(ReplicaMetrics::new("c", "r")),
+        ]
+    }
+
+    /// Collapse whitespace, so queries can be compared regardless of their formatting.
+    fn normalize(query: &str) -> String {
+        query.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn subscribe_query_accepts_all_specs() {
+        let specs = all_specs();
+        let query = subscribe_query(&specs, None);
+        assert_eq!(query.matches("::int4 AS spec").count(), specs.len());
+    }
+
+    #[test]
+    fn subscribe_query_pads_spec_columns() {
+        let specs: Vec<Box<dyn Spec>> = vec![Box::new(Operator), Box::new(Elapsed)];
+
+        let query = subscribe_query(&specs, None);
+        assert_eq!(
+            normalize(&query),
+            "SUBSCRIBE (\
+             SELECT 0::int4 AS spec, \"address\"::text AS \"address\", \"id\"::int8 AS \"id\", \
+             \"name\"::text AS \"name\", NULL::int8 AS \"worker_id\" \
+             FROM mz_introspection.mz_dataflow_operators \
+             JOIN mz_introspection.mz_dataflow_addresses USING (id) \
+             UNION ALL \
+             SELECT 1::int4 AS spec, NULL::text AS \"address\", \"id\"::int8 AS \"id\", \
+             NULL::text AS \"name\", \"worker_id\"::int8 AS \"worker_id\" \
+             FROM mz_introspection.mz_scheduling_elapsed_raw \
+             ) WITH (PROGRESS)",
+        );
+
+        let query = subscribe_query(&specs, Some(Duration::from_millis(1500)));
+        assert!(query.ends_with(") WITH (PROGRESS) AS OF AT LEAST 1500"));
+    }
+
+    fn subscribe(mode: Mode) -> Subscribe {
+        Subscribe {
+            stream: None,
+            specs: vec![Box::new(Elapsed), Box::new(Size)],
+            mode,
+            stash: Vec::new(),
+            up_to: Some(Duration::from_secs(10)),
+            as_of: None,
+            snapshot: None,
+            contents: BTreeMap::new(),
+            seeded: false,
+            failures: 0,
+        }
+    }
+
+    fn diffs(updates: &[Update]) -> Vec<(Data, i64)> {
+        let mut diffs: Vec<_> = updates.iter().map(|u| (u.data.clone(), u.diff)).collect();
+        diffs.sort();
+        diffs
+    }
+
+    #[test]
+    fn reconcile_initial_snapshot() {
+        let mode = Mode::Continual { duration: None };
+        let snapshot = BTreeMap::from([((0, Data::Elapsed(1, 0)), 5), ((1, Data::Size(1, 0)), 3)]);
+
+        // Counter specs skip the initial snapshot in continual mode.
+        let mut sub = subscribe(mode);
+        let time = Duration::from_secs(1);
+        let updates = sub.reconcile(snapshot.clone(), time);
+        assert_eq!(diffs(&updates), [(Data::Size(1, 0), 3)]);
+        assert!(updates.iter().all(|u| u.time == time));
+        assert_eq!(sub.contents, snapshot);
+
+        let mut sub = subscribe(Mode::Snapshot);
+        let updates = sub.reconcile(snapshot, Duration::from_secs(1));
+        assert_eq!(
+            diffs(&updates),
+            [(Data::Elapsed(1, 0), 5), (Data::Size(1, 0), 3)],
+        );
+    }
+
+    #[test]
+    fn reconcile_resumed_snapshot() {
+        let mut sub = subscribe(Mode::Continual { duration: None });
+        sub.contents = BTreeMap::from([
+            ((0, Data::Elapsed(1, 0)), 5),
+            ((1, Data::Size(1, 0)), 3),
+            ((1, Data::Size(2, 0)), 1),
+        ]);
+        sub.seeded = true;
+
+        let snapshot = BTreeMap::from([
+            ((0, Data::Elapsed(1, 0)), 7),
+            ((1, Data::Size(1, 0)), 3),
+            ((1, Data::Size(3, 0)), 2),
+        ]);
+        let updates = sub.reconcile(snapshot.clone(), Duration::from_secs(1));
+        assert_eq!(
+            diffs(&updates),
+            [
+                (Data::Elapsed(1, 0), 2),
+                (Data::Size(2, 0), -1),
+                (Data::Size(3, 0), 2),
+            ],
+        );
+        assert_eq!(sub.contents, snapshot);
+
+        // Snapshots past the end of the subscription are absorbed without emitting changes.
+        let updates = sub.reconcile(BTreeMap::new(), Duration::from_secs(11));
+        assert!(updates.is_empty());
+        assert!(sub.contents.is_empty());
+    }
+
+    #[test]
+    fn reconcile_resumed_during_initial_snapshot() {
+        let mut sub = subscribe(Mode::Continual { duration: None });
+        sub.as_of = Some(Duration::from_secs(1));
+        sub.snapshot = Some(BTreeMap::from([((0, Data::Elapsed(1, 0)), 5)]));
+        assert!(sub.is_resumable());
+
+        // The connection fails before the initial snapshot completes, so the resumed
+        // snapshot is the first one and counter specs still skip it.
+        sub.restart();
+        let snapshot = BTreeMap::from([((0, Data::Elapsed(1, 0)), 7), ((1, Data::Size(1, 0)), 3)]);
+        let updates = sub.reconcile(snapshot.clone(), Duration::from_secs(2));
+        assert_eq!(diffs(&updates), [(Data::Size(1, 0), 3)]);
+        assert_eq!(sub.contents, snapshot);
+    }
+}
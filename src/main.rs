@@ -5,10 +5,13 @@ mod types;
 
 use std::time::Duration;
 
-use clap::{Parser, ValueEnum};
-use futures::TryStreamExt;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use futures::StreamExt;
+use futures::stream;
+use tokio::sync::watch;
 
-use crate::aggregate::Aggregator;
+use crate::aggregate::{Aggregator, StackMode, build_pprof};
 use crate::collect::{Collector, subscribe};
 
 /// Dataflow profiler for Materialize
@@ -20,12 +23,16 @@ struct Args {
     sql_url: String,
 
     /// Target cluster name
-    #[arg(long)]
-    cluster: String,
+    #[arg(long, required_unless_present = "all_clusters")]
+    cluster: Option<String>,
 
-    /// Target replica name
-    #[arg(long)]
-    replica: String,
+    /// Target replica names, defaults to all replicas of the cluster
+    #[arg(long = "replica")]
+    replicas: Vec<String>,
+
+    /// Profile all replicas of all user clusters
+    #[arg(long, conflicts_with_all = ["cluster", "replicas"])]
+    all_clusters: bool,
 
     /// Types of profiles to collect
     #[arg(
@@ -37,9 +44,14 @@ struct Args {
     )]
     profiles: Vec<Profile>,
 
-    /// Profiling duration in seconds
-    #[arg(long)]
-    duration: Option<u64>,
+    /// Profiling duration in seconds, or until interrupted if no value is given
+    #[arg(long, num_args(0..=1))]
+    #[allow(clippy::option_option)]
+    duration: Option<Option<u64>>,
+
+    /// How to build the stacks of operator samples
+    #[arg(long, value_enum, default_value_t = StackMode::Operator)]
+    stacks: StackMode,
 
     /// Output file path
     #[arg(long, default_value_t = String::from("profile.pprof"))]
@@ -50,8 +62,48 @@ struct Args {
 enum Profile {
     /// elapsed time profile
     Time,
+    /// operator schedule count profile
+    Schedules,
+    /// operator activation duration profile
+    Durations,
+    /// worker park time profile
+    Parks,
     /// heap size profile
     Size,
+    /// heap capacity profile
+    Capacity,
+    /// heap allocation count profile
+    Allocations,
+    /// arrangement record count profile
+    Records,
+    /// arrangement batch count profile
+    Batches,
+    /// sent message count profile
+    MessagesSent,
+    /// received message count profile
+    MessagesReceived,
+    /// sent and received message batch count profile
+    MessageBatches,
+    /// dataflow error count profile
+    Errors,
+    /// dataflow hydration time profile
+    Hydration,
+    /// dataflow frontier lag profile
+    Lag,
+    /// peek count and latency profile
+    Peeks,
+    /// replica CPU time and memory not attributed to operators, added to the time and size
+    /// profiles
+    Overhead,
+}
+
+impl Profile {
+    fn needs_channels(&self) -> bool {
+        matches!(
+            self,
+            Self::MessagesSent | Self::MessagesReceived | Self::MessageBatches
+        )
+    }
 }
 
 #[tokio::main]
@@ -61,36 +113,205 @@ async fn main() -> anyhow::Result<()> {
     args.profiles.sort();
     args.profiles.dedup();
 
+    check_profiles(&args);
+
     let mode = match args.duration {
         Some(secs) => {
-            let duration = Some(Duration::from_secs(secs));
+            let duration = secs.map(Duration::from_secs);
             subscribe::Mode::Continual { duration }
         }
         None => subscribe::Mode::Snapshot,
     };
 
-    let mut collector = Collector::new(&args.sql_url, &args.cluster, &args.replica)?;
-    collector.subscribe(subscribe::Operator, mode).await?;
+    let replicas = match (&args.cluster, args.replicas.is_empty()) {
+        (Some(cluster), false) => args
+            .replicas
+            .iter()
+            .map(|replica| (cluster.clone(), replica.clone()))
+            .collect(),
+        (cluster, _) => collect::list_replicas(&args.sql_url, cluster.as_deref()).await?,
+    };
+    if replicas.is_empty() {
+        anyhow::bail!("no replicas to profile");
+    }
 
-    for profile in args.profiles {
-        match profile {
-            Profile::Time => collector.subscribe(subscribe::Elapsed, mode).await?,
-            Profile::Size => collector.subscribe(subscribe::Size, mode).await?,
+    let collectors = replicas
+        .iter()
+        .map(|(cluster, replica)| new_collector(&args, cluster, replica, mode))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // The first signal stops collecting and writes the profile, a second one exits immediately.
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        if let Err(error) = shutdown_signal().await {
+            eprintln!("* failed to listen for shutdown signals: {error}");
+            // Dropping the sender would signal a shutdown, so keep it until the run ends.
+            return std::future::pending().await;
+        }
+        println!("* shutting down, interrupt again to exit immediately");
+        let _ = shutdown_tx.send(true);
+
+        if shutdown_signal().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+
+    let mut streams = Vec::new();
+    let mut aggregators = Vec::new();
+    for (index, collector) in collectors.into_iter().enumerate() {
+        let (cluster, replica) = &replicas[index];
+        let stream = collector.into_stream(shutdown_rx.clone());
+        streams.push(stream.map(move |result| (index, result)));
+        aggregators.push(Aggregator::new(cluster, replica, args.stacks));
+    }
+
+    // A collector's stream ends after its first error, so a failing replica doesn't stop the
+    // others from being profiled.
+    let mut stream = stream::select_all(streams);
+    let mut collected = false;
+    let mut errors = Vec::new();
+    while let Some((index, result)) = stream.next().await {
+        let (cluster, replica) = &replicas[index];
+        match result {
+            Ok(batch) => {
+                println!(
+                    "* processing updates of replica `{cluster}.{replica}` up to time {:?}",
+                    batch.time
+                );
+                aggregators[index].update(batch);
+                collected = true;
+            }
+            Err(error) => {
+                eprintln!("* failed to profile replica `{cluster}.{replica}`: {error:#}");
+                errors.push(error);
+            }
+        }
+    }
+
+    let failed = errors.len() == replicas.len();
+    if collected || !failed {
+        let prof = build_pprof(&aggregators);
+
+        println!("Writing profile to file `{}`", args.output_file);
+        pprof::write_file(&prof, &args.output_file)?;
+    }
+
+    match errors.into_iter().next() {
+        Some(error) if failed => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Exit with a usage error if the requested profiles would produce misleading output.
+fn check_profiles(args: &Args) {
+    // A snapshot only contains the peeks in flight at its time, whose latencies are unknown.
+    if args.profiles.contains(&Profile::Peeks) && args.duration.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the `peeks` profile requires `--duration`, since peeks are only measured in \
+                 continual mode",
+            )
+            .exit();
+    }
+
+    // Replica CPU time is only measured in continual mode, so in snapshot mode the overhead
+    // is only added to the size profile.
+    if args.profiles.contains(&Profile::Overhead) {
+        let time = args.profiles.contains(&Profile::Time);
+        let size = args.profiles.contains(&Profile::Size);
+        let continual = args.duration.is_some();
+        let error = match (time, size) {
+            (false, false) => Some("the `overhead` profile requires the `time` or `size` profile"),
+            (true, false) if !continual => Some(
+                "the `overhead` profile requires the `size` profile or `--duration`, \
+                 since CPU time is only measured in continual mode",
+            ),
+            _ => None,
+        };
+        if let Some(error) = error {
+            Args::command()
+                .error(ErrorKind::MissingRequiredArgument, error)
+                .exit();
+        }
+        if time && !continual {
+            println!("* skipping the overhead of the `time` profile, which requires `--duration`");
         }
     }
+}
 
-    let mut stream = collector.into_stream();
-    let mut aggregator = Aggregator::new();
+/// Wait for Ctrl-C, or for SIGTERM on Unix.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
 
-    while let Some(batch) = stream.try_next().await? {
-        println!("* processing updates up to time {:?}", batch.time);
-        aggregator.update(batch);
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Create a collector subscribing to the data of the given profiles on the given replica.
+fn new_collector(
+    args: &Args,
+    cluster: &str,
+    replica: &str,
+    mode: subscribe::Mode,
+) -> anyhow::Result<Collector> {
+    let mut collector = Collector::new(&args.sql_url, cluster, replica, mode)?;
+    collector.subscribe(subscribe::Operator);
+    collector.subscribe(subscribe::Export);
+
+    match args.stacks {
+        StackMode::Operator => {}
+        StackMode::Lir => collector.subscribe(subscribe::LirMapping),
+        StackMode::Dependencies => collector.subscribe(subscribe::ObjectDependencies),
     }
 
-    let prof = aggregator.build_pprof();
+    if args.profiles.iter().any(Profile::needs_channels) {
+        collector.subscribe(subscribe::Channel);
+    }
 
-    println!("Writing profile to file `{}`", args.output_file);
-    pprof::write_file(&prof, &args.output_file)?;
+    for profile in &args.profiles {
+        match profile {
+            Profile::Time => collector.subscribe(subscribe::Elapsed),
+            Profile::Schedules => collector.subscribe(subscribe::Schedules),
+            Profile::Durations => collector.subscribe(subscribe::Durations),
+            Profile::Parks => collector.subscribe(subscribe::Parks),
+            Profile::Size => {
+                collector.subscribe(subscribe::Size);
+                collector.subscribe(subscribe::Sharing);
+            }
+            Profile::Capacity => collector.subscribe(subscribe::Capacity),
+            Profile::Allocations => collector.subscribe(subscribe::Allocations),
+            Profile::Records => collector.subscribe(subscribe::Records),
+            Profile::Batches => collector.subscribe(subscribe::Batches),
+            Profile::MessagesSent => collector.subscribe(subscribe::MessagesSent),
+            Profile::MessagesReceived => collector.subscribe(subscribe::MessagesReceived),
+            Profile::MessageBatches => {
+                collector.subscribe(subscribe::MessageBatchesSent);
+                collector.subscribe(subscribe::MessageBatchesReceived);
+            }
+            Profile::Errors => collector.subscribe(subscribe::ErrorCounts),
+            Profile::Hydration => {
+                collector.subscribe(subscribe::Hydration::new(cluster, replica));
+            }
+            Profile::Lag => {
+                collector.subscribe(subscribe::ExportFrontiers);
+                collector.subscribe(subscribe::ImportFrontiers);
+            }
+            Profile::Peeks => collector.subscribe(subscribe::Peeks),
+            Profile::Overhead => {
+                collector.subscribe(subscribe::ReplicaMetrics::new(cluster, replica));
+            }
+        }
+    }
 
-    Ok(())
+    Ok(collector)
 }
@@ -1,5 +1,14 @@
+use std::fmt;
+
 pub type OpId = u64;
 pub type WorkerId = u64;
+pub type ChannelId = u64;
+pub type DataflowId = u64;
+pub type ExportId = String;
+pub type PeekId = String;
+pub type ProcessId = u64;
+pub type LirId = u64;
+pub type ObjectId = String;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct OpInfo {
@@ -7,6 +16,27 @@ pub struct OpInfo {
     pub address: Address,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChannelInfo {
+    pub from: Option<OpId>,
+    pub to: Option<OpId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExportInfo {
+    pub dataflow: DataflowId,
+    /// Fully qualified name of the exported SQL object, if any.
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LirInfo {
+    pub operator: String,
+    pub parent: Option<LirId>,
+    /// Range of IDs of the operators rendered for this LIR node, end exclusive.
+    pub operator_ids: (OpId, OpId),
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Address(pub Box<[u64]>);
 
@@ -20,4 +50,14 @@ impl Address {
     pub fn parent(&self) -> Option<Address> {
         self.ancestors().next()
     }
+
+    pub fn is_dataflow_root(&self) -> bool {
+        self.0.len() == 1
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
 }